@@ -15,24 +15,36 @@
 //! }
 //! ```
 
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
 
 /// A Linux kernel module.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Module {
     /// The name of the module.
     pub module: String,
     /// The size of the module.
     pub size: u64,
+    /// The number of references held against this module.
+    pub ref_count: u64,
     /// What is using this module.
-    pub used_by: Vec<String>
+    pub used_by: Vec<String>,
+    /// Whether the module is fully live, or in the process of (un)loading.
+    pub state: ModuleState,
+    /// The kernel memory offset the module was loaded at.
+    pub offset: u64,
+    /// Kernel taint flags attributed to this module, if any.
+    pub taints: Vec<TaintFlag>,
 }
 
 impl Module {
     /// Parse an individual /proc/modules-like line.
     pub fn parse(line: &str) -> io::Result<Module> {
-        let mut parts = line.split(' ');
+        let mut parts = line.trim_end().split(' ');
 
         let name = parts.next().ok_or_else(|| io::Error::new(
             io::ErrorKind::InvalidData,
@@ -44,17 +56,43 @@ impl Module {
             "size not found"
         ))?;
 
-        let used_by = parts.nth(1).ok_or_else(|| io::Error::new(
+        let ref_count = parts.next().ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidData,
+            "ref count not found"
+        ))?;
+
+        let used_by = parts.next().ok_or_else(|| io::Error::new(
             io::ErrorKind::InvalidData,
             "used_by not found"
         ))?;
 
+        // Older kernels may not report a load state, memory offset, or
+        // taint flags, so these trailing fields are treated as optional.
+        let state = match parts.next() {
+            Some(state) => ModuleState::parse(state)?,
+            None => ModuleState::Live,
+        };
+
+        let offset = match parts.next() {
+            Some(offset) => parse_offset(offset)?,
+            None => 0,
+        };
+
+        let taints = match parts.next() {
+            Some(taints) => parse_taints(taints)?,
+            None => vec![],
+        };
+
         Ok(Module {
             module: name.to_string(),
             size: size.parse::<u64>().map_err(|_| io::Error::new(
                 io::ErrorKind::InvalidData,
                 "module size is not a number"
             ))?,
+            ref_count: ref_count.parse::<u64>().map_err(|_| io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ref count is not a number"
+            ))?,
             used_by: if used_by == "-" {
                 vec![]
             } else {
@@ -62,7 +100,10 @@ impl Module {
                     .map(String::from)
                     .filter(|x| !x.is_empty())
                     .collect()
-            }
+            },
+            state,
+            offset,
+            taints,
         })
     }
 
@@ -75,6 +116,93 @@ impl Module {
     pub fn all() -> io::Result<Vec<Module>> {
         ModuleIter::new()?.collect()
     }
+
+    /// Look up a single loaded module by name.
+    pub fn by_name(name: &str) -> io::Result<Option<Module>> {
+        for module in ModuleIter::new()? {
+            let module = module?;
+
+            if module.module == name {
+                return Ok(Some(module));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check whether a module with the given name is currently loaded.
+    pub fn is_loaded(name: &str) -> io::Result<bool> {
+        Ok(Self::by_name(name)?.is_some())
+    }
+}
+
+/// The load state of a module, as reported in the fifth column of
+/// `/proc/modules`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleState {
+    /// The module is fully loaded and active.
+    Live,
+    /// The module is in the process of being loaded.
+    Loading,
+    /// The module is in the process of being unloaded.
+    Unloading,
+}
+
+impl ModuleState {
+    fn parse(state: &str) -> io::Result<Self> {
+        match state {
+            "Live" => Ok(ModuleState::Live),
+            "Loading" => Ok(ModuleState::Loading),
+            "Unloading" => Ok(ModuleState::Unloading),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unrecognized module state"
+            ))
+        }
+    }
+}
+
+/// A kernel taint flag attributed to a module, as reported in the optional
+/// trailing `(...)` group of `/proc/modules`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaintFlag {
+    /// `P`: module was loaded from out-of-tree, proprietary sources.
+    Proprietary,
+    /// `O`: module was loaded from out-of-tree sources.
+    OutOfTree,
+    /// `E`: module is unsigned.
+    Unsigned,
+}
+
+impl TaintFlag {
+    fn parse(flag: char) -> Option<Self> {
+        match flag {
+            'P' => Some(TaintFlag::Proprietary),
+            'O' => Some(TaintFlag::OutOfTree),
+            'E' => Some(TaintFlag::Unsigned),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the hexadecimal `0x...` memory offset field.
+fn parse_offset(offset: &str) -> io::Result<u64> {
+    u64::from_str_radix(offset.trim_start_matches("0x"), 16).map_err(|_| io::Error::new(
+        io::ErrorKind::InvalidData,
+        "module offset is not a hexadecimal number"
+    ))
+}
+
+/// Parse the optional `(POE)`-style trailing taint flag group.
+fn parse_taints(taints: &str) -> io::Result<Vec<TaintFlag>> {
+    let taints = taints.trim_start_matches('(').trim_end_matches(')');
+
+    taints.chars()
+        .map(|flag| TaintFlag::parse(flag).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unrecognized taint flag"
+        )))
+        .collect()
 }
 
 /// Read module entries iteratively.
@@ -105,6 +233,371 @@ impl Iterator for ModuleIter {
     }
 }
 
+/// A snapshot of the loaded modules, indexed by name, that can answer
+/// dependency questions and work out a safe order to remove them in.
+pub struct ModuleSet {
+    modules: Modules,
+}
+
+impl ModuleSet {
+    /// Take a snapshot of the currently-loaded modules.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self::from_modules(Module::all()?))
+    }
+
+    fn from_modules(modules: Vec<Module>) -> Self {
+        ModuleSet { modules: Modules::from_modules(modules) }
+    }
+
+    /// The underlying name-indexed snapshot, e.g. for
+    /// [`Modules::match_device`].
+    pub fn modules(&self) -> &Modules {
+        &self.modules
+    }
+
+    /// The modules that depend on `name` (i.e. that would break if `name`
+    /// were removed).
+    pub fn dependents_of(&self, name: &str) -> Vec<&Module> {
+        match self.modules.get(name) {
+            Some(module) => module.used_by.iter()
+                .filter_map(|dependent| self.modules.get(dependent))
+                .collect(),
+            None => Vec::new()
+        }
+    }
+
+    /// The modules that `name` depends on (i.e. that must stay loaded for
+    /// `name` to keep working).
+    pub fn dependencies_of(&self, name: &str) -> Vec<&Module> {
+        self.modules.values()
+            .filter(|module| module.used_by.iter().any(|used_by| used_by == name))
+            .collect()
+    }
+
+    /// Work out an order in which every module in this set can be
+    /// `rmmod`'d one at a time without removing a module something else
+    /// still depends on.
+    pub fn removal_order(&self) -> io::Result<Vec<&Module>> {
+        let mut in_degree: HashMap<&str, usize> = self.modules.values()
+            .map(|module| (module.module.as_str(), module.used_by.len()))
+            .collect();
+
+        // The reverse edges: for each module, the modules it depends on
+        // (those whose `used_by` lists it), precomputed once rather than
+        // rescanned per dequeue.
+        let mut dependencies: HashMap<&str, Vec<&str>> = HashMap::new();
+        for module in self.modules.values() {
+            for dependent in &module.used_by {
+                dependencies.entry(dependent.as_str()).or_default().push(module.module.as_str());
+            }
+        }
+
+        let mut queue: VecDeque<&str> = in_degree.iter()
+            .filter(|&(_, &degree)| degree == 0)
+            .map(|(&name, _)| name)
+            .collect();
+
+        let mut order = Vec::with_capacity(self.modules.len());
+
+        while let Some(name) = queue.pop_front() {
+            order.push(self.modules.get(name).unwrap());
+
+            for &dependency in dependencies.get(name).into_iter().flatten() {
+                let degree = in_degree.get_mut(dependency).unwrap();
+                *degree -= 1;
+
+                if *degree == 0 {
+                    queue.push_back(dependency);
+                }
+            }
+        }
+
+        if order.len() < self.modules.len() {
+            let stuck: Vec<&str> = self.modules.keys()
+                .filter(|name| !order.iter().any(|module| module.module == *name))
+                .collect();
+
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("reference cycle detected among: {}", stuck.join(", "))
+            ));
+        }
+
+        Ok(order)
+    }
+}
+
+/// A change observed between two snapshots of `/proc/modules`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleEvent {
+    /// A module was loaded that wasn't present before.
+    Loaded(Module),
+    /// A module that was previously loaded is no longer present.
+    Unloaded(String),
+    /// A still-loaded module's reference count changed.
+    RefCountChanged {
+        /// The name of the module.
+        module: String,
+        /// The reference count in the previous snapshot.
+        old: u64,
+        /// The reference count in the latest snapshot.
+        new: u64,
+    },
+}
+
+/// How long to wait before re-polling `/proc/modules` after a poll found
+/// nothing new to report.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches `/proc/modules` for `modprobe`/`rmmod` activity and yields the
+/// changes between snapshots as a stream of events.
+pub struct ModuleMonitor {
+    previous: HashMap<String, Module>,
+    pending: VecDeque<ModuleEvent>,
+    source: Box<dyn FnMut() -> io::Result<Vec<Module>>>,
+}
+
+impl ModuleMonitor {
+    /// Begin watching from the current set of loaded modules.
+    pub fn new() -> io::Result<Self> {
+        Self::with_source(Box::new(Module::all))
+    }
+
+    fn with_source(mut source: Box<dyn FnMut() -> io::Result<Vec<Module>>>) -> io::Result<Self> {
+        let previous = snapshot(source()?);
+
+        Ok(ModuleMonitor {
+            previous,
+            pending: VecDeque::new(),
+            source,
+        })
+    }
+
+    #[cfg(test)]
+    fn from_modules(modules: Vec<Module>) -> Self {
+        ModuleMonitor {
+            previous: snapshot(modules),
+            pending: VecDeque::new(),
+            source: Box::new(Module::all),
+        }
+    }
+
+    /// Take a fresh snapshot and queue up the events between it and the
+    /// previous one.
+    fn poll(&mut self) -> io::Result<()> {
+        let current = snapshot((self.source)()?);
+
+        self.diff_into(current);
+
+        Ok(())
+    }
+
+    /// Diff `current` against the previous snapshot, queue the resulting
+    /// events, and make `current` the new previous snapshot.
+    fn diff_into(&mut self, current: HashMap<String, Module>) {
+        for (name, module) in &current {
+            match self.previous.get(name) {
+                None => self.pending.push_back(ModuleEvent::Loaded(module.clone())),
+                Some(previous) if previous.ref_count != module.ref_count => {
+                    self.pending.push_back(ModuleEvent::RefCountChanged {
+                        module: name.clone(),
+                        old: previous.ref_count,
+                        new: module.ref_count,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        for name in self.previous.keys() {
+            if !current.contains_key(name) {
+                self.pending.push_back(ModuleEvent::Unloaded(name.clone()));
+            }
+        }
+
+        self.previous = current;
+    }
+}
+
+fn snapshot(modules: Vec<Module>) -> HashMap<String, Module> {
+    modules.into_iter().map(|module| (module.module.clone(), module)).collect()
+}
+
+impl Iterator for ModuleMonitor {
+    type Item = io::Result<ModuleEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+
+            if let Err(why) = self.poll() {
+                return Some(Err(why));
+            }
+
+            if self.pending.is_empty() {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// A snapshot of the loaded modules, indexed by name, for O(1) membership
+/// and lookup queries.
+pub struct Modules {
+    modules: HashMap<String, Module>,
+}
+
+impl Modules {
+    /// Take a snapshot of the currently-loaded modules.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self::from_modules(Module::all()?))
+    }
+
+    fn from_modules(modules: Vec<Module>) -> Self {
+        Modules {
+            modules: modules.into_iter()
+                .map(|module| (module.module.clone(), module))
+                .collect()
+        }
+    }
+
+    /// Fetch a module by name.
+    pub fn get(&self, name: &str) -> Option<&Module> {
+        self.modules.get(name)
+    }
+
+    /// Check whether a module with the given name is in this snapshot.
+    pub fn contains(&self, name: &str) -> bool {
+        self.modules.contains_key(name)
+    }
+
+    /// The number of modules using the named module, if it is loaded.
+    pub fn used_by_count(&self, name: &str) -> Option<usize> {
+        self.modules.get(name).map(|module| module.used_by.len())
+    }
+
+    fn values(&self) -> impl Iterator<Item = &Module> {
+        self.modules.values()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.modules.keys().map(String::as_str)
+    }
+
+    fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Find the loaded modules whose `modules.alias` entry matches the
+    /// given modalias (e.g. `pci:v000010DEd...`), mirroring the
+    /// vendor/device id matching the kernel performs when binding a
+    /// driver to a device.
+    pub fn match_device(&self, modalias: &str) -> io::Result<Vec<&Module>> {
+        Ok(self.match_against(&load_aliases()?, modalias))
+    }
+
+    fn match_against(&self, aliases: &[AliasRule], modalias: &str) -> Vec<&Module> {
+        aliases.iter()
+            .filter(|rule| rule.matches(modalias))
+            .filter_map(|rule| self.get(&rule.module))
+            .collect()
+    }
+}
+
+/// A single `modules.alias` rule mapping a glob pattern to the module
+/// that claims matching devices.
+#[derive(Debug, Clone, PartialEq)]
+struct AliasRule {
+    pattern: String,
+    module: String,
+}
+
+impl AliasRule {
+    fn matches(&self, modalias: &str) -> bool {
+        glob_match(&self.pattern, modalias)
+    }
+}
+
+/// Parse `alias <pattern> <module>` lines from a `modules.alias` file,
+/// ignoring comments and any other directives it may contain.
+fn parse_aliases<'a, I: Iterator<Item = &'a str>>(lines: I) -> Vec<AliasRule> {
+    lines.filter_map(|line| {
+        let mut parts = line.split_whitespace();
+
+        if parts.next()? != "alias" {
+            return None;
+        }
+
+        Some(AliasRule {
+            pattern: parts.next()?.to_string(),
+            module: parts.next()?.to_string(),
+        })
+    }).collect()
+}
+
+/// Load and parse the `modules.alias` file for the running kernel.
+fn load_aliases() -> io::Result<Vec<AliasRule>> {
+    let release = kernel_release()?;
+    let path = format!("/lib/modules/{}/modules.alias", release.trim());
+    let content = std::fs::read_to_string(path)?;
+
+    Ok(parse_aliases(content.lines()))
+}
+
+/// The running kernel's release string, as reported by `uname -r`.
+fn kernel_release() -> io::Result<String> {
+    let output = Command::new("uname").arg("-r").output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other("uname -r did not exit successfully"));
+    }
+
+    String::from_utf8(output.stdout)
+        .map(|release| release.trim().to_string())
+        .map_err(|_| io::Error::new(
+            io::ErrorKind::InvalidData,
+            "uname -r did not print valid UTF-8"
+        ))
+}
+
+/// Match `text` against a `fnmatch`-style glob `pattern` supporting `*`
+/// (any run of characters) and `?` (any single character), as used by
+/// `modules.alias` patterns.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Indices into `pattern`/`text` of the most recent `*`, so backtracking
+    // can retry it against a longer chunk of `text` when a literal match
+    // fails further along.
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+
+    p == pattern.len()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -122,35 +615,257 @@ nvidia_drm 40960 11 - Live 0x0000000000000000 (POE)"#;
                 Module {
                     module: "snd_hda_intel".into(),
                     size: 40960,
-                    used_by: vec![]
+                    ref_count: 9,
+                    used_by: vec![],
+                    state: ModuleState::Live,
+                    offset: 0,
+                    taints: vec![],
                 },
                 Module {
                     module: "snd_hda_codec".into(),
                     size: 126_976,
+                    ref_count: 4,
                     used_by: vec![
                         "snd_hda_codec_hdmi".into(),
                         "snd_hda_codec_realtek".into(),
                         "snd_hda_codec_generic".into(),
                         "snd_hda_intel".into(),
-                    ]
+                    ],
+                    state: ModuleState::Live,
+                    offset: 0,
+                    taints: vec![],
                 },
                 Module {
                     module: "snd_hda_core".into(),
                     size: 81920,
+                    ref_count: 5,
                     used_by: vec![
                         "snd_hda_codec_hdmi".into(),
                         "snd_hda_codec_realtek".into(),
                         "snd_hda_codec_generic".into(),
                         "snd_hda_intel".into(),
                         "snd_hda_codec".into(),
-                    ]
+                    ],
+                    state: ModuleState::Live,
+                    offset: 0,
+                    taints: vec![],
                 },
                 Module {
                     module: "nvidia_drm".into(),
                     size: 40960,
-                    used_by: vec![]
+                    ref_count: 11,
+                    used_by: vec![],
+                    state: ModuleState::Live,
+                    offset: 0,
+                    taints: vec![
+                        TaintFlag::Proprietary,
+                        TaintFlag::OutOfTree,
+                        TaintFlag::Unsigned,
+                    ],
                 },
             ]
         )
     }
+
+    #[test]
+    fn tolerates_missing_state_offset_and_taints() {
+        let module = Module::parse("snd_hda_intel 40960 9 -").unwrap();
+
+        assert_eq!(module.state, ModuleState::Live);
+        assert_eq!(module.offset, 0);
+        assert_eq!(module.taints, vec![]);
+    }
+
+    fn stub_module(name: &str, used_by: &[&str]) -> Module {
+        Module {
+            module: name.into(),
+            size: 0,
+            ref_count: used_by.len() as u64,
+            used_by: used_by.iter().map(|&s| s.to_string()).collect(),
+            state: ModuleState::Live,
+            offset: 0,
+            taints: vec![],
+        }
+    }
+
+    #[test]
+    fn removal_order_places_dependents_before_dependencies() {
+        // c depends on b, which depends on a.
+        let set = ModuleSet::from_modules(vec![
+            stub_module("a", &["b"]),
+            stub_module("b", &["c"]),
+            stub_module("c", &[]),
+        ]);
+
+        let order: Vec<&str> = set.removal_order().unwrap()
+            .into_iter()
+            .map(|module| module.module.as_str())
+            .collect();
+
+        assert_eq!(order, vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn removal_order_detects_reference_cycles() {
+        let set = ModuleSet::from_modules(vec![
+            stub_module("a", &["b"]),
+            stub_module("b", &["a"]),
+        ]);
+
+        assert!(set.removal_order().is_err());
+    }
+
+    #[test]
+    fn dependents_and_dependencies_are_looked_up_by_name() {
+        let set = ModuleSet::from_modules(Module::parse_from(SAMPLE.lines()).unwrap());
+
+        let dependents: Vec<&str> = set.dependents_of("snd_hda_codec")
+            .into_iter()
+            .map(|module| module.module.as_str())
+            .collect();
+        assert_eq!(dependents, vec!["snd_hda_intel"]);
+
+        let mut dependencies: Vec<&str> = set.dependencies_of("snd_hda_intel")
+            .into_iter()
+            .map(|module| module.module.as_str())
+            .collect();
+        dependencies.sort();
+        assert_eq!(dependencies, vec!["snd_hda_codec", "snd_hda_core"]);
+    }
+
+    #[test]
+    fn monitor_detects_loaded_unloaded_and_ref_count_changes() {
+        let mut monitor = ModuleMonitor::from_modules(vec![
+            stub_module("snd_hda_intel", &[]),
+            stub_module("snd_hda_codec", &["snd_hda_intel"]),
+        ]);
+
+        let mut next = stub_module("snd_hda_intel", &[]);
+        next.ref_count = 3;
+
+        monitor.diff_into(snapshot(vec![
+            next,
+            stub_module("nvidia_drm", &[]),
+        ]));
+
+        let mut events: Vec<ModuleEvent> = std::iter::from_fn(|| monitor.pending.pop_front()).collect();
+        events.sort_by_key(|event| match event {
+            ModuleEvent::Loaded(module) => module.module.clone(),
+            ModuleEvent::Unloaded(name) => name.clone(),
+            ModuleEvent::RefCountChanged { module, .. } => module.clone(),
+        });
+
+        assert_eq!(events, vec![
+            ModuleEvent::Loaded(stub_module("nvidia_drm", &[])),
+            ModuleEvent::Unloaded("snd_hda_codec".into()),
+            ModuleEvent::RefCountChanged {
+                module: "snd_hda_intel".into(),
+                old: 0,
+                new: 3,
+            },
+        ]);
+    }
+
+    #[test]
+    fn next_polls_through_a_no_op_poll_until_an_event_appears() {
+        // The first snapshot seeds `previous`; the second is identical
+        // (a no-op poll, which is what every `ModuleMonitor::new()` caller
+        // sees first since `previous` is seeded from the same snapshot);
+        // the third actually differs, so `next()` must keep polling
+        // through the no-op in between instead of giving up on it.
+        let snapshots = [
+            vec![stub_module("snd_hda_intel", &[])],
+            vec![stub_module("snd_hda_intel", &[])],
+            vec![stub_module("nvidia_drm", &[])],
+        ];
+        let calls = std::cell::Cell::new(0);
+
+        let mut monitor = ModuleMonitor::with_source(Box::new(move || {
+            let index = calls.get().min(snapshots.len() - 1);
+            calls.set(calls.get() + 1);
+            Ok(snapshots[index].clone())
+        })).unwrap();
+
+        let mut events = vec![
+            monitor.next().unwrap().unwrap(),
+            monitor.next().unwrap().unwrap(),
+        ];
+        events.sort_by_key(|event| match event {
+            ModuleEvent::Loaded(module) => module.module.clone(),
+            ModuleEvent::Unloaded(name) => name.clone(),
+            ModuleEvent::RefCountChanged { module, .. } => module.clone(),
+        });
+
+        assert_eq!(events, vec![
+            ModuleEvent::Loaded(stub_module("nvidia_drm", &[])),
+            ModuleEvent::Unloaded("snd_hda_intel".into()),
+        ]);
+    }
+
+    #[test]
+    fn modules_collection_looks_up_by_name() {
+        let modules = Modules::from_modules(Module::parse_from(SAMPLE.lines()).unwrap());
+
+        assert!(modules.contains("snd_hda_intel"));
+        assert!(!modules.contains("bluetooth"));
+        assert_eq!(modules.get("nvidia_drm").unwrap().ref_count, 11);
+        assert_eq!(modules.get("bluetooth"), None);
+        assert_eq!(modules.used_by_count("snd_hda_codec"), Some(4));
+        assert_eq!(modules.used_by_count("bluetooth"), None);
+    }
+
+    #[test]
+    fn glob_matches_wildcards_and_single_chars() {
+        assert!(glob_match("pci:v000010DEd*sv*sd*bc03sc*i*", "pci:v000010DEd00001C82sv00001028sd00000776bc03sc00i00"));
+        assert!(glob_match("usb:v1D6Bp0002d*", "usb:v1D6Bp0002d0515"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(!glob_match("pci:v000010DEd*", "pci:v000010DCd0001"));
+    }
+
+    #[test]
+    fn parses_alias_file_lines() {
+        let aliases = parse_aliases(
+            "alias pci:v000010DEd*sv*sd*bc03sc*i* nvidia_drm\n\
+             # a comment is ignored\n\
+             alias snd-card-0 snd_hda_intel".lines()
+        );
+
+        assert_eq!(aliases, vec![
+            AliasRule {
+                pattern: "pci:v000010DEd*sv*sd*bc03sc*i*".into(),
+                module: "nvidia_drm".into(),
+            },
+            AliasRule {
+                pattern: "snd-card-0".into(),
+                module: "snd_hda_intel".into(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn matches_modalias_against_loaded_modules() {
+        let modules = Modules::from_modules(Module::parse_from(SAMPLE.lines()).unwrap());
+        let aliases = vec![
+            AliasRule {
+                pattern: "pci:v000010DEd*".into(),
+                module: "nvidia_drm".into(),
+            },
+            AliasRule {
+                pattern: "pci:v00008086d*".into(),
+                module: "snd_hda_intel".into(),
+            },
+            AliasRule {
+                pattern: "pci:v000010DEd*".into(),
+                module: "not_loaded".into(),
+            },
+        ];
+
+        let matches: Vec<&str> = modules.match_against(&aliases, "pci:v000010DEd00001C82sv0sd0bc0sc0i0")
+            .into_iter()
+            .map(|module| module.module.as_str())
+            .collect();
+
+        assert_eq!(matches, vec!["nvidia_drm"]);
+    }
 }